@@ -6,6 +6,10 @@ type NodeIter<'a> = std::iter::Peekable<std::slice::Iter<'a, Node>>;
 #[derive(Debug)]
 pub enum RenderError {
     DuplicateParamName(String, Range),
+    // A `{> include ... with foo` param that isn't declared anywhere in the including template's
+    // own scope (via `{> with}`) - the generated call would reference an identifier Gleam can't
+    // resolve.
+    UndeclaredIncludeParam(String, Range),
 }
 
 #[derive(Debug)]
@@ -14,16 +18,154 @@ struct Context {
     pub imports: Vec<String>,
     pub functions: Vec<String>,
     pub typed_params: Vec<(String, String)>,
+    // One entry per `{% block %}`: (param_name, default_function_name). Gleam has no default
+    // argument syntax, so these can't just live in `typed_params` with a default value - they're
+    // threaded separately into a `render_builder_with_blocks` overload instead.
+    pub block_params: Vec<(String, String)>,
     pub includes_for_loop: bool,
     pub has_template_content: bool,
+    pub uses_escape: bool,
+    pub used_filters: Vec<String>,
+    pub includes_loop_metadata: bool,
+    // Set when `loop.index` or `loop.length` (the `Int` fields of `LoopInfo`) are interpolated
+    // directly, so `render` knows to bring in `gleam/int` for the `int.to_string` conversion.
+    pub uses_int_to_string: bool,
+}
+
+// Generated once per file, and only when a `{% for %}` body references `loop.index`/`loop.first`/
+// `loop.last`/`loop.length`.
+const LOOP_INFO_TYPE: &str = r#"type LoopInfo {
+  LoopInfo(index: Int, first: Bool, last: Bool, length: Int)
+}"#;
+
+// Generated once per file, and only when the template actually escapes an identifier, so that
+// plain templates don't carry a dependency on `gleam/string` they never use.
+const ESCAPE_FUNCTION: &str = r#"fn escape(value: String) -> String {
+    value
+    |> string.replace("&", "&amp;")
+    |> string.replace("<", "&lt;")
+    |> string.replace(">", "&gt;")
+    |> string.replace("\"", "&quot;")
+    |> string.replace("'", "&#39;")
+}"#;
+
+// The standard filter set, generated into `context.functions` the first time a template uses
+// one of them. Anything else is assumed to be a plain Gleam function resolved through the
+// template's own `{> import` lines.
+const STANDARD_FILTERS: &[(&str, &str)] = &[
+    (
+        "uppercase",
+        r#"fn uppercase(value: String) -> String {
+    string.uppercase(value)
+}"#,
+    ),
+    (
+        "lowercase",
+        r#"fn lowercase(value: String) -> String {
+    string.lowercase(value)
+}"#,
+    ),
+    (
+        "trim",
+        r#"fn trim(value: String) -> String {
+    string.trim(value)
+}"#,
+    ),
+    (
+        "truncate",
+        r#"fn truncate(value: String, length: Int) -> String {
+    string.slice(value, 0, length)
+}"#,
+    ),
+];
+
+// Whether a `{% for %}` body refers to the `loop` record, checked structurally rather than by
+// grepping the generated source so that unrelated text or identifiers containing "loop." can't
+// false-trigger it, and so that a nested for-loop's own `loop` (which shadows ours) doesn't
+// either.
+fn references_loop_variable(nodes: &[Node]) -> bool {
+    nodes.iter().any(|node| match node {
+        Node::Text(_) | Node::Import(_) | Node::With(_, _) => false,
+        Node::Identifier(expression) | Node::RawIdentifier(expression) | Node::Builder(expression) => {
+            expression_references_loop(expression)
+        }
+        Node::Filtered(base_expression, filters) => {
+            expression_references_loop(base_expression)
+                || filters
+                    .iter()
+                    .any(|(_, args)| args.iter().any(|arg| expression_references_loop(arg)))
+        }
+        Node::If(identifier_name, if_nodes, else_nodes) => {
+            expression_references_loop(identifier_name)
+                || references_loop_variable(if_nodes)
+                || references_loop_variable(else_nodes)
+        }
+        Node::For(_, _, list_identifier, _loop_nodes, else_nodes, _, _) => {
+            // `_loop_nodes` is a separate scope: that nested loop binds its own `loop`, which
+            // shadows ours, so it doesn't count as a reference to *our* loop variable.
+            expression_references_loop(list_identifier)
+                || else_nodes
+                    .as_ref()
+                    .map(|nodes| references_loop_variable(nodes))
+                    .unwrap_or(false)
+        }
+        Node::Trim(inner, _, _) => references_loop_variable(std::slice::from_ref(inner.as_ref())),
+        Node::BlockFunction(_, _, _, _)
+        | Node::Block(_, _)
+        | Node::Extends(_, _, _)
+        | Node::Include(_, _) => false,
+    })
+}
+
+fn expression_references_loop(expression: &str) -> bool {
+    expression == "loop" || expression.starts_with("loop.")
+}
+
+// `loop.index` and `loop.length` are `Int` fields of `LoopInfo`, but `{{ }}` interpolation always
+// builds a `String` - so these two need converting with `int.to_string` before they can be
+// escaped/appended like any other identifier. `loop.first`/`loop.last` are `Bool` and aren't
+// meaningful to interpolate directly, so they're left alone here (same as any other non-String
+// expression - they're only useful inside `{% if %}`).
+fn is_int_loop_field(expression: &str) -> bool {
+    expression == "loop.index" || expression == "loop.length"
+}
+
+// Auto-escaping defaults on for HTML-ish output (matching askama's own default), but an explicit
+// `escape` argument from the caller always wins.
+fn default_escape(from_file_name: &str) -> bool {
+    from_file_name.ends_with(".html") || from_file_name.ends_with(".htm")
 }
 
 pub fn render(
     iter: &mut NodeIter,
     prog_name: &str,
     from_file_name: &str,
+    escape: Option<bool>,
 ) -> Result<String, RenderError> {
-    let context = render_lines(iter)?;
+    let escape = escape.unwrap_or_else(|| default_escape(from_file_name));
+    let mut context = render_lines(iter, escape)?;
+
+    let mut prelude_functions = Vec::new();
+    for (filter_name, function_text) in STANDARD_FILTERS {
+        if context.used_filters.iter().any(|name| name == filter_name) {
+            prelude_functions.push(function_text.to_string());
+        }
+    }
+    if context.includes_loop_metadata {
+        prelude_functions.push(LOOP_INFO_TYPE.to_string());
+    }
+    if context.uses_escape {
+        prelude_functions.push(ESCAPE_FUNCTION.to_string());
+    }
+    context.functions.splice(0..0, prelude_functions);
+
+    // gleam/string is needed both for escaping and for the standard filter set; skip the
+    // synthetic import if the template already imports it itself.
+    let uses_string = context.uses_escape || !context.used_filters.is_empty();
+    let user_imports_string = context
+        .imports
+        .iter()
+        .any(|details| details == "gleam/string" || details.starts_with("gleam/string."));
 
     let import_lines = context
         .imports
@@ -58,10 +200,84 @@ pub fn render(
         ""
     };
 
+    let string_import = if uses_string && !user_imports_string {
+        "import gleam/string\n"
+    } else {
+        ""
+    };
+
+    // `loop.index`/`loop.length` interpolation needs `int.to_string`; skip the synthetic import
+    // if the template already imports `gleam/int` itself.
+    let user_imports_int = context
+        .imports
+        .iter()
+        .any(|details| details == "gleam/int" || details.starts_with("gleam/int."));
+    let int_import = if context.uses_int_to_string && !user_imports_int {
+        "import gleam/int\n"
+    } else {
+        ""
+    };
+
     let render_functions = if context.has_template_content {
-        format!(
-            r#"
+        if context.block_params.is_empty() {
+            format!(
+                r#"
+pub fn render_builder({params_string}) -> StringBuilder {{
+    let builder = string_builder.from_string("")
+{builder_lines}
+    builder
+}}
+
+pub fn render({params_string}) -> String {{
+    string_builder.to_string(render_builder({args_string}))
+}}
+"#,
+                params_string = params_string,
+                builder_lines = context.builder_lines,
+                args_string = args_string
+            )
+        } else {
+            // Gleam has no default argument syntax, so a base template with `{% block %}`
+            // regions gets a plain no-arg-for-blocks `render_builder` that forwards each block's
+            // own default function, plus a `render_builder_with_blocks` overload that an
+            // `{> extends` child calls directly with its overriding block closures.
+            let block_params_string = context
+                .block_params
+                .iter()
+                .map(|(param_name, _)| {
+                    format!("{} {}: fn() -> StringBuilder", param_name, param_name)
+                })
+                .collect::<Vec<_>>()
+                .join(", ");
+
+            let block_defaults_string = context
+                .block_params
+                .iter()
+                .map(|(param_name, default_function_name)| {
+                    format!("{}: {}", param_name, default_function_name)
+                })
+                .collect::<Vec<_>>()
+                .join(", ");
+
+            let with_blocks_params_string = if params_string.is_empty() {
+                block_params_string
+            } else {
+                format!("{}, {}", params_string, block_params_string)
+            };
+
+            let forward_args_string = if args_string.is_empty() {
+                block_defaults_string
+            } else {
+                format!("{}, {}", args_string, block_defaults_string)
+            };
+
+            format!(
+                r#"
 pub fn render_builder({params_string}) -> StringBuilder {{
+    render_builder_with_blocks({forward_args_string})
+}}
+
+pub fn render_builder_with_blocks({with_blocks_params_string}) -> StringBuilder {{
     let builder = string_builder.from_string("")
 {builder_lines}
     builder
@@ -71,10 +287,13 @@ pub fn render({params_string}) -> String {{
     string_builder.to_string(render_builder({args_string}))
 }}
 "#,
-            params_string = params_string,
-            builder_lines = context.builder_lines,
-            args_string = args_string
-        )
+                params_string = params_string,
+                forward_args_string = forward_args_string,
+                with_blocks_params_string = with_blocks_params_string,
+                builder_lines = context.builder_lines,
+                args_string = args_string
+            )
+        }
     } else {
         String::new()
     };
@@ -83,13 +302,15 @@ pub fn render({params_string}) -> String {{
         r#"// DO NOT EDIT: Code generated by {prog_name} from {source_file}
 
 import gleam/string_builder.{{type StringBuilder}}
-{list_import}
+{list_import}{string_import}{int_import}
 {import_lines}{functions}
 {render_functions}
 "#,
         prog_name = prog_name,
         source_file = from_file_name,
         list_import = list_import,
+        string_import = string_import,
+        int_import = int_import,
         import_lines = import_lines,
         render_functions = render_functions,
     );
@@ -97,7 +318,65 @@ import gleam/string_builder.{{type StringBuilder}}
     Ok(output)
 }
 
-fn render_lines(iter: &mut NodeIter) -> Result<Context, RenderError> {
+// A `Node::Trim` wrapped around a whole `{% for %}` only reaches the text immediately outside
+// the loop. The open tag's own trailing `-` (`{%- for ... -%}`) and the close tag's own leading
+// `-` (`{%- endfor -%}`) instead need to reach *inside* the loop, trimming the body's own
+// first/last `Node::Text` - so those two are threaded onto `Node::For` itself and applied here,
+// before the rest of the body is walked normally.
+fn render_loop_body(
+    loop_nodes: &[Node],
+    escape: bool,
+    trim_body_start: bool,
+    trim_body_end: bool,
+) -> Result<Context, RenderError> {
+    let mut nodes = loop_nodes;
+    let mut prefix_lines = String::new();
+    let mut prefix_has_content = false;
+
+    if trim_body_start {
+        if let [Node::Text(text), rest @ ..] = nodes {
+            // A lone body text is also the last one, and a text followed by a `{%- ... %}` tag
+            // loses its trailing whitespace as well, as in `render_lines`.
+            let trimmed = match rest {
+                [] if trim_body_end => text.trim(),
+                [Node::Trim(_, true, _), ..] => text.trim(),
+                _ => text.trim_start(),
+            };
+            prefix_lines.push_str(&format!(
+                "    let builder = string_builder.append(builder, \"{}\")\n",
+                trimmed.replace('\"', "\\\"")
+            ));
+            prefix_has_content = !trimmed.trim().is_empty();
+            nodes = rest;
+        }
+    }
+
+    let mut suffix_lines = String::new();
+    let mut suffix_has_content = false;
+    if trim_body_end {
+        if let [rest @ .., Node::Text(text)] = nodes {
+            // A text preceded by a `{% ... -%}` tag loses its leading whitespace as well.
+            let trimmed = match rest {
+                [.., Node::Trim(_, _, true)] => text.trim(),
+                _ => text.trim_end(),
+            };
+            suffix_lines.push_str(&format!(
+                "    let builder = string_builder.append(builder, \"{}\")\n",
+                trimmed.replace('\"', "\\\"")
+            ));
+            suffix_has_content = !trimmed.trim().is_empty();
+            nodes = rest;
+        }
+    }
+
+    let mut context = render_lines(&mut nodes.iter().peekable(), escape)?;
+    context.builder_lines = format!("{}{}{}", prefix_lines, context.builder_lines, suffix_lines);
+    context.has_template_content =
+        context.has_template_content || prefix_has_content || suffix_has_content;
+    Ok(context)
+}
+
+fn render_lines(iter: &mut NodeIter, escape: bool) -> Result<Context, RenderError> {
     let mut builder_lines = String::new();
     let mut imports = vec![];
     let mut functions = vec![];
@@ -106,13 +385,25 @@ fn render_lines(iter: &mut NodeIter) -> Result<Context, RenderError> {
     // some control, though parameters are labelled and can be called in any order. Some kind of
     // order is required to keep the tests passing as it seems to be non-determinate in a HashMap
     let mut typed_params = Vec::new();
+    let mut block_params = Vec::new();
     let mut includes_for_loop = false;
     let mut has_template_content = false;
+    let mut uses_escape = false;
+    let mut used_filters: Vec<String> = Vec::new();
+    let mut includes_loop_metadata = false;
+    let mut uses_int_to_string = false;
 
     loop {
         match iter.peek() {
             Some(Node::Text(text)) => {
                 iter.next();
+
+                // A following `{%- ... -%}` or `{{- ... -}}` tag trims our trailing whitespace.
+                let text = match iter.peek() {
+                    Some(Node::Trim(_, true, _)) => text.trim_end(),
+                    _ => text.as_str(),
+                };
+
                 builder_lines.push_str(&format!(
                     "    let builder = string_builder.append(builder, \"{}\")\n",
                     text.replace('\"', "\\\"")
@@ -123,6 +414,55 @@ fn render_lines(iter: &mut NodeIter) -> Result<Context, RenderError> {
                 has_template_content = has_template_content || !text.trim().is_empty();
             }
             Some(Node::Identifier(name)) => {
+                iter.next();
+                let mut value = if is_int_loop_field(name) {
+                    uses_int_to_string = true;
+                    format!("int.to_string({})", name)
+                } else {
+                    name.clone()
+                };
+                if escape {
+                    uses_escape = true;
+                    value = format!("escape({})", value);
+                }
+                builder_lines.push_str(&format!(
+                    "    let builder = string_builder.append(builder, {})\n",
+                    value
+                ));
+                has_template_content = true;
+            }
+            Some(Node::Filtered(base_expression, filters)) => {
+                iter.next();
+
+                let mut value = base_expression.clone();
+                for (filter_name, extra_args) in filters {
+                    if STANDARD_FILTERS
+                        .iter()
+                        .any(|(name, _)| name == filter_name)
+                        && !used_filters.contains(filter_name)
+                    {
+                        used_filters.push(filter_name.clone());
+                    }
+
+                    value = if extra_args.is_empty() {
+                        format!("{}({})", filter_name, value)
+                    } else {
+                        format!("{}({}, {})", filter_name, value, extra_args.join(", "))
+                    };
+                }
+
+                if escape {
+                    uses_escape = true;
+                    value = format!("escape({})", value);
+                }
+
+                builder_lines.push_str(&format!(
+                    "    let builder = string_builder.append(builder, {})\n",
+                    value
+                ));
+                has_template_content = true;
+            }
+            Some(Node::RawIdentifier(name)) => {
                 iter.next();
                 builder_lines.push_str(&format!(
                     "    let builder = string_builder.append(builder, {})\n",
@@ -157,8 +497,8 @@ fn render_lines(iter: &mut NodeIter) -> Result<Context, RenderError> {
             }
             Some(Node::If(identifier_name, if_nodes, else_nodes)) => {
                 iter.next();
-                let if_context = render_lines(&mut if_nodes.iter().peekable())?;
-                let else_context = render_lines(&mut else_nodes.iter().peekable())?;
+                let if_context = render_lines(&mut if_nodes.iter().peekable(), escape)?;
+                let else_context = render_lines(&mut else_nodes.iter().peekable(), escape)?;
                 builder_lines.push_str(&format!(
                     r#"    let builder = case {} {{
         True -> {{
@@ -176,9 +516,29 @@ fn render_lines(iter: &mut NodeIter) -> Result<Context, RenderError> {
                 includes_for_loop = includes_for_loop
                     || if_context.includes_for_loop
                     || else_context.includes_for_loop;
+                uses_escape = uses_escape || if_context.uses_escape || else_context.uses_escape;
+                uses_int_to_string = uses_int_to_string
+                    || if_context.uses_int_to_string
+                    || else_context.uses_int_to_string;
+                includes_loop_metadata = includes_loop_metadata
+                    || if_context.includes_loop_metadata
+                    || else_context.includes_loop_metadata;
+                for filter_name in if_context.used_filters.into_iter().chain(else_context.used_filters) {
+                    if !used_filters.contains(&filter_name) {
+                        used_filters.push(filter_name);
+                    }
+                }
                 has_template_content = true;
             }
-            Some(Node::For(entry_identifier, entry_type, list_identifier, loop_nodes)) => {
+            Some(Node::For(
+                entry_identifier,
+                entry_type,
+                list_identifier,
+                loop_nodes,
+                else_nodes,
+                trim_body_start,
+                trim_body_end,
+            )) => {
                 iter.next();
 
                 let entry_type = entry_type
@@ -186,17 +546,80 @@ fn render_lines(iter: &mut NodeIter) -> Result<Context, RenderError> {
                     .map(|value| format!(": {}", value))
                     .unwrap_or_else(|| "".to_string());
 
-                let loop_context = render_lines(&mut loop_nodes.iter().peekable())?;
-                builder_lines.push_str(&format!(
-                    r#"    let builder = list.fold({}, builder, fn(builder, {}{}) {{
+                let loop_context =
+                    render_loop_body(loop_nodes, escape, *trim_body_start, *trim_body_end)?;
+
+                // Only compute the list length, and pay for the extra traversal it costs, when
+                // the body actually references the `loop` record.
+                let uses_loop_metadata = references_loop_variable(loop_nodes);
+
+                let fold_expression = if uses_loop_metadata {
+                    includes_loop_metadata = true;
+                    format!(
+                        r#"{{
+        let loop_length = list.length({list_identifier})
+        list.index_fold({list_identifier}, builder, fn(builder, {entry_identifier}{entry_type}, loop_index) {{
+            let loop = LoopInfo(index: loop_index, first: loop_index == 0, last: loop_index == loop_length - 1, length: loop_length)
+            {body}
+            builder
+    }})
+}}"#,
+                        list_identifier = list_identifier,
+                        entry_identifier = entry_identifier,
+                        entry_type = entry_type,
+                        body = loop_context.builder_lines,
+                    )
+                } else {
+                    format!(
+                        r#"list.fold({}, builder, fn(builder, {}{}) {{
         {}
         builder
-}})
+}})"#,
+                        list_identifier, entry_identifier, entry_type, loop_context.builder_lines
+                    )
+                };
+
+                match else_nodes {
+                    Some(else_nodes) => {
+                        let else_context = render_lines(&mut else_nodes.iter().peekable(), escape)?;
+                        builder_lines.push_str(&format!(
+                            r#"    let builder = case {list_identifier} {{
+        [] -> {{
+            {else_lines}
+            builder
+        }}
+        _ -> {fold_expression}
+}}
 "#,
-                    list_identifier, entry_identifier, entry_type, loop_context.builder_lines
-                ));
+                            list_identifier = list_identifier,
+                            else_lines = else_context.builder_lines,
+                            fold_expression = fold_expression,
+                        ));
+                        uses_escape = uses_escape || else_context.uses_escape;
+                        uses_int_to_string = uses_int_to_string || else_context.uses_int_to_string;
+                        includes_loop_metadata =
+                            includes_loop_metadata || else_context.includes_loop_metadata;
+                        for filter_name in else_context.used_filters {
+                            if !used_filters.contains(&filter_name) {
+                                used_filters.push(filter_name);
+                            }
+                        }
+                    }
+                    None => {
+                        builder_lines
+                            .push_str(&format!("    let builder = {}\n", fold_expression));
+                    }
+                }
 
                 includes_for_loop = true;
+                uses_escape = uses_escape || loop_context.uses_escape;
+                uses_int_to_string = uses_int_to_string || loop_context.uses_int_to_string;
+                includes_loop_metadata = includes_loop_metadata || loop_context.includes_loop_metadata;
+                for filter_name in loop_context.used_filters {
+                    if !used_filters.contains(&filter_name) {
+                        used_filters.push(filter_name);
+                    }
+                }
                 has_template_content = true;
             }
             Some(Node::BlockFunction(visiblity, head, body_nodes, _range)) => {
@@ -205,7 +628,7 @@ fn render_lines(iter: &mut NodeIter) -> Result<Context, RenderError> {
                     Visibility::Private => "",
                     Visibility::Public => "pub ",
                 };
-                let body_context = render_lines(&mut body_nodes.iter().peekable())?;
+                let body_context = render_lines(&mut body_nodes.iter().peekable(), escape)?;
                 let body = body_context.builder_lines;
                 functions.push(format!(
                     r#"{visibility_text}fn {head} -> StringBuilder {{
@@ -216,6 +639,198 @@ fn render_lines(iter: &mut NodeIter) -> Result<Context, RenderError> {
                 ));
 
                 includes_for_loop = includes_for_loop || body_context.includes_for_loop;
+                uses_escape = uses_escape || body_context.uses_escape;
+                uses_int_to_string = uses_int_to_string || body_context.uses_int_to_string;
+                includes_loop_metadata = includes_loop_metadata || body_context.includes_loop_metadata;
+                for filter_name in body_context.used_filters {
+                    if !used_filters.contains(&filter_name) {
+                        used_filters.push(filter_name);
+                    }
+                }
+            }
+            Some(Node::Block(name, body_nodes)) => {
+                iter.next();
+
+                let block_function_name = format!("block_{}", name);
+                let body_context = render_lines(&mut body_nodes.iter().peekable(), escape)?;
+                functions.push(format!(
+                    r#"pub fn {block_function_name}() -> StringBuilder {{
+    let builder = string_builder.from_string("")
+{body}
+    builder
+}}"#,
+                    block_function_name = block_function_name,
+                    body = body_context.builder_lines,
+                ));
+
+                // A block is an overridable region: it renders through a labelled, function-
+                // valued parameter so an `{> extends` child can pass its own override while a
+                // stand-alone base template still falls back to the block's own default body.
+                // The block function itself is `pub` so a child that overrides only *some* of the
+                // base's blocks can still forward the rest by referencing `base.block_<name>`
+                // directly, rather than needing a default it can't express (Gleam has none).
+                block_params.push((name.clone(), block_function_name));
+                builder_lines.push_str(&format!(
+                    "    let builder = string_builder.append_builder(builder, {}())\n",
+                    name
+                ));
+
+                includes_for_loop = includes_for_loop || body_context.includes_for_loop;
+                uses_escape = uses_escape || body_context.uses_escape;
+                uses_int_to_string = uses_int_to_string || body_context.uses_int_to_string;
+                includes_loop_metadata = includes_loop_metadata || body_context.includes_loop_metadata;
+                for filter_name in body_context.used_filters {
+                    if !used_filters.contains(&filter_name) {
+                        used_filters.push(filter_name);
+                    }
+                }
+                has_template_content = true;
+            }
+            Some(Node::Extends(base_module, base_block_names, overriding_blocks)) => {
+                iter.next();
+
+                imports.push(base_module.clone());
+
+                let mut override_args = Vec::new();
+                for (name, block_body_nodes) in overriding_blocks {
+                    let block_context = render_lines(&mut block_body_nodes.iter().peekable(), escape)?;
+                    override_args.push(format!(
+                        r#"{name}: fn() {{
+    let builder = string_builder.from_string("")
+{body}
+    builder
+}}"#,
+                        name = name,
+                        body = block_context.builder_lines,
+                    ));
+
+                    includes_for_loop = includes_for_loop || block_context.includes_for_loop;
+                    uses_escape = uses_escape || block_context.uses_escape;
+                    uses_int_to_string = uses_int_to_string || block_context.uses_int_to_string;
+                    includes_loop_metadata =
+                        includes_loop_metadata || block_context.includes_loop_metadata;
+                    for filter_name in block_context.used_filters {
+                        if !used_filters.contains(&filter_name) {
+                            used_filters.push(filter_name);
+                        }
+                    }
+                }
+
+                // `render_builder_with_blocks` has no defaults to fall back on (Gleam has none),
+                // so every block it declares must be supplied. A block this child doesn't
+                // override still needs an argument - forward the base's own (`pub`) block
+                // function unchanged, so partial overrides compile the same as a full override.
+                for block_name in base_block_names {
+                    if !overriding_blocks.iter().any(|(name, _)| name == block_name) {
+                        override_args.push(format!(
+                            "{name}: {base_module}.block_{name}",
+                            name = block_name,
+                            base_module = base_module,
+                        ));
+                    }
+                }
+
+                // A base with no blocks at all can just use its plain, no-block-args
+                // `render_builder`; any base with blocks needs the `_with_blocks` overload.
+                let call = if override_args.is_empty() {
+                    format!("{}.render_builder()", base_module)
+                } else {
+                    format!(
+                        "{}.render_builder_with_blocks({})",
+                        base_module,
+                        override_args.join(", ")
+                    )
+                };
+                builder_lines.push_str(&format!(
+                    "    let builder = string_builder.append_builder(builder, {})\n",
+                    call
+                ));
+                has_template_content = true;
+            }
+            Some(Node::Include(other_template, params)) => {
+                iter.next();
+
+                let mut seen_param_names: Vec<&String> = Vec::new();
+                for (param_name, range) in params {
+                    if seen_param_names.contains(&param_name) {
+                        return Err(RenderError::DuplicateParamName(
+                            param_name.clone(),
+                            range.clone(),
+                        ));
+                    }
+                    seen_param_names.push(param_name);
+
+                    // Params are forwarded by name, so the including template must already have
+                    // declared each one via its own `{> with}` - otherwise this is a reference to
+                    // an identifier that doesn't exist here.
+                    if !typed_params.iter().any(|(name, _)| name == param_name) {
+                        return Err(RenderError::UndeclaredIncludeParam(
+                            param_name.clone(),
+                            range.clone(),
+                        ));
+                    }
+                }
+
+                imports.push(other_template.clone());
+
+                let args_string = params
+                    .iter()
+                    .map(|(param_name, _)| format!("{}: {}", param_name, param_name))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+
+                builder_lines.push_str(&format!(
+                    "    let builder = string_builder.append_builder(builder, {}.render_builder({}))\n",
+                    other_template, args_string
+                ));
+                has_template_content = true;
+            }
+            Some(Node::Trim(inner, _trim_left, trim_right)) => {
+                iter.next();
+
+                // Trailing whitespace on the preceding text node was already stripped when that
+                // `Node::Text` was rendered, above, by peeking ahead to here. This only reaches
+                // text *outside* the wrapped node - a compound node's own internal trim (e.g. a
+                // `{%- for -%}`'s own body edges) is threaded onto that node itself instead; see
+                // `render_loop_body`.
+
+                let inner_nodes = std::slice::from_ref(inner.as_ref());
+                let inner_context = render_lines(&mut inner_nodes.iter().peekable(), escape)?;
+
+                builder_lines.push_str(&inner_context.builder_lines);
+                imports.extend(inner_context.imports);
+                functions.extend(inner_context.functions);
+                typed_params.extend(inner_context.typed_params);
+                block_params.extend(inner_context.block_params);
+                includes_for_loop = includes_for_loop || inner_context.includes_for_loop;
+                has_template_content = has_template_content || inner_context.has_template_content;
+                uses_escape = uses_escape || inner_context.uses_escape;
+                uses_int_to_string = uses_int_to_string || inner_context.uses_int_to_string;
+                includes_loop_metadata = includes_loop_metadata || inner_context.includes_loop_metadata;
+                for filter_name in inner_context.used_filters {
+                    if !used_filters.contains(&filter_name) {
+                        used_filters.push(filter_name);
+                    }
+                }
+
+                // A trailing `-` strips leading whitespace off the text immediately following
+                // this tag, so consume and trim it here rather than in the plain `Node::Text` arm.
+                if *trim_right {
+                    if let Some(Node::Text(text)) = iter.peek() {
+                        iter.next();
+                        // That text may itself precede a `{%- ... %}` tag, which strips its
+                        // trailing whitespace, as in the `Node::Text` arm.
+                        let trimmed = match iter.peek() {
+                            Some(Node::Trim(_, true, _)) => text.trim(),
+                            _ => text.trim_start(),
+                        };
+                        builder_lines.push_str(&format!(
+                            "    let builder = string_builder.append(builder, \"{}\")\n",
+                            trimmed.replace('\"', "\\\"")
+                        ));
+                        has_template_content = has_template_content || !trimmed.trim().is_empty();
+                    }
+                }
             }
             None => break,
         }
@@ -226,8 +841,13 @@ fn render_lines(iter: &mut NodeIter) -> Result<Context, RenderError> {
         imports,
         functions,
         typed_params,
+        block_params,
         includes_for_loop,
         has_template_content,
+        uses_escape,
+        used_filters,
+        includes_loop_metadata,
+        uses_int_to_string,
     })
 }
 
@@ -257,6 +877,9 @@ mod test {
     #[macro_export]
     macro_rules! assert_render {
         ($text:expr $(,)?) => {{
+            assert_render!($text, true)
+        }};
+        ($text:expr, $escape:expr $(,)?) => {{
             let _ = env_logger::try_init();
             let result = scanner::scan($text)
                 .map_err(|err| Error::Scan(err))
@@ -264,7 +887,7 @@ mod test {
                     parser::parse(&mut tokens.iter().peekable()).map_err(|err| Error::Parse(err))
                 })
                 .and_then(|ast| {
-                    render(&mut ast.iter().peekable(), NAME, "-test-")
+                    render(&mut ast.iter().peekable(), NAME, "-test-", Some($escape))
                         .map_err(|err| Error::Render(err))
                 });
             insta::assert_snapshot!(insta::internals::AutoName, format_result(result), $text);
@@ -295,6 +918,73 @@ Hello {{ name }}, {{ adjective }} to meet you"
         );
     }
 
+    #[test]
+    fn test_render_identifier_is_escaped_when_enabled() {
+        assert_render!(
+            "{> with name as String
+Hello {{ name }}, good to meet you"
+        );
+    }
+
+    #[test]
+    fn test_default_escape_is_html_extension_based() {
+        assert!(default_escape("templates/page.html"));
+        assert!(default_escape("templates/page.htm"));
+        assert!(!default_escape("templates/page.txt"));
+        assert!(!default_escape("-test-"));
+    }
+
+    #[test]
+    fn test_render_identifier_escaping_disabled() {
+        assert_render!(
+            "{> with name as String
+Hello {{ name }}, good to meet you",
+            false
+        );
+    }
+
+    #[test]
+    fn test_render_raw_identifier_is_never_escaped() {
+        assert_render!(
+            "{> with name as String
+Hello {! name !}, good to meet you"
+        );
+    }
+
+    #[test]
+    fn test_render_escaping_does_not_duplicate_a_user_gleam_string_import() {
+        assert_render!(
+            "{> import gleam/string
+{> with name as String
+Hello {{ name }}, {{ string.uppercase(name) }}"
+        );
+    }
+
+    #[test]
+    fn test_render_single_filter() {
+        assert_render!(
+            "{> with name as String
+Hello {{ name | uppercase }}, good to meet you"
+        );
+    }
+
+    #[test]
+    fn test_render_filter_chain() {
+        assert_render!(
+            "{> with name as String
+Hello {{ name | uppercase | truncate(10) }}, good to meet you"
+        );
+    }
+
+    #[test]
+    fn test_render_custom_filter() {
+        assert_render!(
+            "{> import my_filters
+{> with name as String
+Hello {{ name | my_filters.shout }}, good to meet you"
+        );
+    }
+
     #[test]
     fn test_render_gleam_expression() {
         assert_render!(
@@ -357,6 +1047,48 @@ Hello,{% for item in list %} to {{ item }} and {% endfor %} everyone else"
         );
     }
 
+    #[test]
+    fn test_render_for_loop_with_loop_index() {
+        assert_render!(
+            "{> with list as List(String)
+{% for item in list %}{{ loop.index }}: {{ item }}\n{% endfor %}"
+        );
+    }
+
+    #[test]
+    fn test_render_for_loop_with_loop_first_and_last() {
+        assert_render!(
+            "{> with list as List(String)
+{% for item in list %}{% if loop.first %}first {% endif %}{{ item }}{% if loop.last %} last{% endif %}{% endfor %}"
+        );
+    }
+
+    #[test]
+    fn test_render_for_loop_outer_does_not_use_inner_loop_metadata() {
+        // The outer loop never references `loop` itself, only the nested loop does, so the
+        // outer `list.fold` should stay plain and not bind an unused `loop` record.
+        assert_render!(
+            "{> with list as List(List(String))
+{% for inner_list in list %}{% for item in inner_list %}{{ loop.index }}: {{ item }}\n{% endfor %}{% endfor %}"
+        );
+    }
+
+    #[test]
+    fn test_render_for_else() {
+        assert_render!(
+            "{> with list as List(String)
+Hello,{% for item in list %} to {{ item }} and {% else %}No items{% endfor %} everyone else"
+        );
+    }
+
+    #[test]
+    fn test_render_for_else_with_loop_metadata() {
+        assert_render!(
+            "{> with list as List(String)
+{% for item in list %}{{ loop.index }}: {{ item }}\n{% else %}No items{% endfor %}"
+        );
+    }
+
     #[test]
     fn test_render_for_as_loop() {
         assert_render!(
@@ -393,6 +1125,18 @@ Hello{% if user.is_admin %} Admin{% endif %}"
         assert_render!("{> import user.{User}\n{> with user as User\n{{ user }}");
     }
 
+    #[test]
+    fn test_render_multiline_with_trim_markers() {
+        assert_render!(
+            r#"{> with my_list as List(String)
+<ul>
+{%- for entry in my_list -%}
+    <li>{{ entry }}</li>
+{%- endfor -%}
+</ul>"#
+        );
+    }
+
     #[test]
     fn test_render_multiline() {
         assert_render!(
@@ -426,6 +1170,30 @@ Hello {[ name ]}, good to meet you"
         assert_render!("Hello {[ string_builder.from_strings([\"Anna\", \" and \", \"Bob\"]) ]}, good to meet you");
     }
 
+    #[test]
+    fn test_render_block_default() {
+        assert_render!("Hello {% block body %}default{% endblock %}");
+    }
+
+    #[test]
+    fn test_render_include() {
+        assert_render!(
+            "{> with name as String
+{> with adjective as String
+{> include greeting with name, adjective"
+        );
+    }
+
+    #[test]
+    fn test_render_include_undeclared_param() {
+        assert_render!("{> include greeting with name, adjective");
+    }
+
+    #[test]
+    fn test_render_extends_with_override() {
+        assert_render!("{> extends base\n{% block body %}Override content{% endblock %}");
+    }
+
     #[test]
     fn test_render_function() {
         assert_render!("{> fn classes()\na b c d\n{> endfn\nHello world");